@@ -0,0 +1,74 @@
+//! Inverse of the `framizer` binary: reads a ring item stream of frames
+//! and reassembles them back into the traces that produced them.
+//!
+//! Reuses `Config`'s flags, but for this direction `input_path` names
+//! the frame stream to read and `output_path` names the trace file to
+//! write - the opposite of `framizer`'s roles - so the default paths are
+//! swapped too, rather than silently inheriting `framizer`'s
+//! `traces.dat`/`frames.evt` defaults.  `--checksums` must match how the
+//! frame stream was written; compression is detected from the stream
+//! header, so `--compress` has no effect here.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use framizer::{Config, FrameReader, FramizerError, Reassembler, Trace};
+
+fn default_config() -> Config {
+    Config {
+        input_path: PathBuf::from("frames.evt"),
+        output_path: PathBuf::from("traces.dat"),
+        ..Config::default()
+    }
+}
+
+fn main() -> ExitCode {
+    let config = match Config::from_args_seeded(default_config(), std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(&config) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(config: &Config) -> Result<(), FramizerError> {
+    let frame_file = File::open(&config.input_path)?;
+    let mut trace_file = File::create(&config.output_path)?;
+
+    let mut frame_reader = FrameReader::from_config(frame_file, config)?;
+    let mut reassembler = Reassembler::new();
+
+    while let Some(frame) = frame_reader.read_next_frame()? {
+        for trace in reassembler.push_frame(&frame) {
+            write_trace(&mut trace_file, &trace)?;
+        }
+    }
+
+    if let Some(trace) = reassembler.finish() {
+        write_trace(&mut trace_file, &trace)?;
+    }
+
+    Ok(())
+}
+
+/// Write a trace back out in the same layout `TraceReader` expects:
+/// timestamp, sample count, then the samples themselves.
+fn write_trace(w: &mut impl Write, trace: &Trace) -> Result<(), FramizerError> {
+    w.write_all(&trace.timestamp.to_le_bytes())?;
+    w.write_all(&(trace.data.len() as u32).to_le_bytes())?;
+    for sample in &trace.data {
+        w.write_all(&sample.to_le_bytes())?;
+    }
+    Ok(())
+}
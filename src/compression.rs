@@ -0,0 +1,66 @@
+//! Optional whole-stream compression for the output frame stream.
+//!
+//! Compression is applied to the *stream*, not per-frame, so the
+//! encoder's window spans many frames and can exploit the baseline
+//! redundancy between consecutive frames of the same trace.
+
+use std::io::{self, Write};
+
+use flate2::write::DeflateEncoder;
+pub use flate2::Compression as Level;
+
+/// Written at the start of the stream, before the first ring item, when
+/// compression is enabled.  Lets a matching reader tell a deflate-wrapped
+/// `frames.evt` apart from a legacy uncompressed one.
+pub const COMPRESSED_MAGIC: [u8; 4] = *b"FRMZ";
+pub const ALG_DEFLATE: u8 = 1;
+
+/// The writer side of a `FrameWriter`'s underlying `Write`, switched
+/// between a raw passthrough and a deflate encoder depending on whether
+/// compression was requested.
+pub enum Sink<W: Write> {
+    Plain(W),
+    Deflate(DeflateEncoder<W>),
+}
+
+impl<W: Write> Sink<W> {
+    pub fn plain(inner: W) -> Self {
+        Sink::Plain(inner)
+    }
+
+    /// Write the stream header and switch to a deflate encoder.
+    pub fn compress(self, level: Level) -> io::Result<Self> {
+        let mut inner = match self {
+            Sink::Plain(w) => w,
+            Sink::Deflate(enc) => enc.finish()?,
+        };
+        inner.write_all(&COMPRESSED_MAGIC)?;
+        inner.write_all(&[ALG_DEFLATE])?;
+        Ok(Sink::Deflate(DeflateEncoder::new(inner, level)))
+    }
+
+    /// Flush and finalize the encoder (a no-op for the plain sink) and
+    /// hand back the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Sink::Plain(w) => Ok(w),
+            Sink::Deflate(enc) => enc.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Sink<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Plain(w) => w.write(buf),
+            Sink::Deflate(enc) => enc.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Plain(w) => w.flush(),
+            Sink::Deflate(enc) => enc.flush(),
+        }
+    }
+}
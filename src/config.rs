@@ -0,0 +1,123 @@
+//! Runtime configuration for the Framizer CLI.
+//!
+//! `FRAME_LENGTH`, `TRACE_FRAME_ITEM_TYPE`, the source id/barrier type
+//! tagging written into every ring item, and the input/output paths
+//! used to be compile-time constants in `main`.  `Config` pulls them
+//! out so the same binary can reframe with a different window or tag a
+//! different digitizer source without a rebuild.
+
+use std::path::PathBuf;
+
+use crate::{Level, FRAME_LENGTH, TRACE_FRAME_ITEM_TYPE};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub frame_length: u64,
+    pub item_type: u32,
+    pub source_id: u32,
+    pub barrier_type: u32,
+    pub input_path: PathBuf,
+    pub output_path: PathBuf,
+    pub checksums: bool,
+    pub compression: Option<Level>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            frame_length: FRAME_LENGTH,
+            item_type: TRACE_FRAME_ITEM_TYPE,
+            source_id: 0,
+            barrier_type: 0,
+            input_path: PathBuf::from("traces.dat"),
+            output_path: PathBuf::from("frames.evt"),
+            checksums: false,
+            compression: None,
+        }
+    }
+}
+
+impl Config {
+    /// Parse `--flag value` pairs, falling back to `Config::default()`
+    /// for anything not given on the command line.
+    ///
+    /// Recognized flags: `--frame-length`, `--item-type`, `--source-id`,
+    /// `--barrier-type`, `--input`, `--output`, `--checksums`,
+    /// `--compress[=level]`.  `--checksums` and `--compress` don't take
+    /// a space-separated value like the others: `--checksums` is a bare
+    /// on/off switch, and `--compress` defaults to `Level::default()`
+    /// unless given as `--compress=<0-9>`.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Config, String> {
+        Config::from_args_seeded(Config::default(), args)
+    }
+
+    /// Like [`Config::from_args`], but starts from `base` instead of
+    /// `Config::default()` - for binaries whose natural defaults (e.g.
+    /// which path is read vs. written) differ from `framizer`'s.
+    pub fn from_args_seeded<I: IntoIterator<Item = String>>(
+        base: Config,
+        args: I,
+    ) -> Result<Config, String> {
+        let mut config = base;
+        let mut args = args.into_iter();
+
+        while let Some(flag) = args.next() {
+            if flag == "--checksums" {
+                config.checksums = true;
+                continue;
+            }
+
+            if flag == "--compress" {
+                config.compression = Some(Level::default());
+                continue;
+            }
+            if let Some(level) = flag.strip_prefix("--compress=") {
+                let level: u32 = level
+                    .parse()
+                    .map_err(|_| format!("invalid --compress level: {}", level))?;
+                config.compression = Some(Level::new(level));
+                continue;
+            }
+
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} requires a value", flag))?;
+
+            match flag.as_str() {
+                "--frame-length" => {
+                    let frame_length: u64 = value
+                        .parse()
+                        .map_err(|_| format!("invalid --frame-length: {}", value))?;
+                    if !(1..=u16::MAX as u64).contains(&frame_length) {
+                        return Err(format!(
+                            "--frame-length must be between 1 and {}, got {}",
+                            u16::MAX,
+                            frame_length
+                        ));
+                    }
+                    config.frame_length = frame_length;
+                }
+                "--item-type" => {
+                    config.item_type = value
+                        .parse()
+                        .map_err(|_| format!("invalid --item-type: {}", value))?
+                }
+                "--source-id" => {
+                    config.source_id = value
+                        .parse()
+                        .map_err(|_| format!("invalid --source-id: {}", value))?
+                }
+                "--barrier-type" => {
+                    config.barrier_type = value
+                        .parse()
+                        .map_err(|_| format!("invalid --barrier-type: {}", value))?
+                }
+                "--input" => config.input_path = PathBuf::from(value),
+                "--output" => config.output_path = PathBuf::from(value),
+                other => return Err(format!("unrecognized flag: {}", other)),
+            }
+        }
+
+        Ok(config)
+    }
+}
@@ -0,0 +1,19 @@
+//! CRC32 (IEEE, reflected) used as a per-frame integrity trailer.
+
+/// Compute the IEEE CRC32 (polynomial 0xEDB88320, reflected) of `data`.
+pub fn crc32_ieee(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFFFFFF;
+
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            if crc & 1 != 0 {
+                crc = (crc >> 1) ^ 0xEDB88320;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+
+    crc ^ 0xFFFFFFFF
+}
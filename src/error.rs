@@ -0,0 +1,72 @@
+//! Error types for the Framizer framing engine.
+
+use std::fmt;
+
+/// Errors that can arise while reading traces or writing frames.
+#[derive(Debug)]
+pub enum FramizerError {
+    /// A plain I/O failure (disk full, permission denied, etc).
+    Io(std::io::Error),
+    /// A trace header announced `expected` samples but the stream ended
+    /// after only `got` of them were read.  Unlike a clean EOF between
+    /// traces, this means the tail of the input is a half-written,
+    /// unusable record.
+    TruncatedTrace { expected: u32, got: u32 },
+    /// Writing a frame out as a ring item failed.
+    RingItemWrite(std::io::Error),
+    /// A frame's trailing CRC32 didn't match the CRC32 recomputed over its
+    /// body, meaning the frame (or the file around it) was corrupted.
+    BadChecksum {
+        frame_start: u64,
+        expected: u32,
+        found: u32,
+    },
+    /// A frame's body didn't parse as either the single-segment or the
+    /// pileup layout - the stream is corrupt or was written by something
+    /// else entirely.
+    MalformedFrame { frame_start: u64 },
+}
+
+impl fmt::Display for FramizerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FramizerError::Io(e) => write!(f, "I/O error: {}", e),
+            FramizerError::TruncatedTrace { expected, got } => write!(
+                f,
+                "truncated trace: expected {} samples, got {}",
+                expected, got
+            ),
+            FramizerError::RingItemWrite(e) => write!(f, "failed to write ring item: {}", e),
+            FramizerError::BadChecksum {
+                frame_start,
+                expected,
+                found,
+            } => write!(
+                f,
+                "bad checksum in frame starting at 0x{:x}: expected 0x{:08x}, found 0x{:08x}",
+                frame_start, expected, found
+            ),
+            FramizerError::MalformedFrame { frame_start } => {
+                write!(f, "malformed frame starting at 0x{:x}", frame_start)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FramizerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FramizerError::Io(e) => Some(e),
+            FramizerError::TruncatedTrace { .. } => None,
+            FramizerError::RingItemWrite(e) => Some(e),
+            FramizerError::BadChecksum { .. } => None,
+            FramizerError::MalformedFrame { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for FramizerError {
+    fn from(e: std::io::Error) -> Self {
+        FramizerError::Io(e)
+    }
+}
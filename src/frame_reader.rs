@@ -0,0 +1,387 @@
+//! Inverse of [`crate::FrameWriter`]: parses `TRACE_FRAME_ITEM_TYPE` ring
+//! items back into `Frame`s, and reassembles consecutive frames of a
+//! multi-frame trace back into the original `Trace`.
+
+use std::collections::VecDeque;
+use std::io::{self, Chain, Cursor, Read};
+
+use flate2::read::DeflateDecoder;
+use rust_ringitem_format::RingItem;
+
+use crate::compression::{ALG_DEFLATE, COMPRESSED_MAGIC};
+use crate::{verify_frame_checksum, Config, Frame, FramizerError, Segment, Trace, TRACE_FRAME_ITEM_TYPE};
+
+/// Inverse of `compression::Sink`: detects the magic/algorithm byte a
+/// compressing `FrameWriter` writes at the start of the stream and
+/// transparently decompresses from then on; otherwise reads the stream
+/// as-is.  Whichever the prefix bytes turn out to be, they're fed back in
+/// via `Chain` so nothing is lost to the probe.
+enum Source<R: Read> {
+    Plain(Chain<Cursor<Vec<u8>>, R>),
+    Deflate(DeflateDecoder<Chain<Cursor<Vec<u8>>, R>>),
+}
+
+impl<R: Read> Source<R> {
+    fn detect(mut inner: R) -> io::Result<Self> {
+        let mut prefix = vec![0u8; COMPRESSED_MAGIC.len()];
+        let mut filled = 0;
+        while filled < prefix.len() {
+            let n = inner.read(&mut prefix[filled..])?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        prefix.truncate(filled);
+
+        if prefix == COMPRESSED_MAGIC {
+            let mut alg = [0u8; 1];
+            inner.read_exact(&mut alg)?;
+            if alg[0] != ALG_DEFLATE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unsupported compression algorithm byte: {}", alg[0]),
+                ));
+            }
+            Ok(Source::Deflate(DeflateDecoder::new(
+                Cursor::new(Vec::new()).chain(inner),
+            )))
+        } else {
+            Ok(Source::Plain(Cursor::new(prefix).chain(inner)))
+        }
+    }
+}
+
+impl<R: Read> Read for Source<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Source::Plain(r) => r.read(buf),
+            Source::Deflate(r) => r.read(buf),
+        }
+    }
+}
+
+/// Reads `Frame`s back out of a ring item stream written by `FrameWriter`.
+pub struct FrameReader<R: Read> {
+    reader: Source<R>,
+    checksums: bool,
+    item_type: u32,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Probes `inner` for the compressed-stream header written by
+    /// `FrameWriter::with_compression` and transparently wraps it in a
+    /// `DeflateDecoder` if present.
+    pub fn new(inner: R) -> io::Result<Self> {
+        Ok(FrameReader {
+            reader: Source::detect(inner)?,
+            checksums: false,
+            item_type: TRACE_FRAME_ITEM_TYPE,
+        })
+    }
+
+    /// Build a reader from a [`Config`]: only recognize the item type
+    /// the config says the matching `FrameWriter` was tagging with, and
+    /// expect a trailing CRC32 per frame iff the config says so.
+    pub fn from_config(inner: R, config: &Config) -> io::Result<Self> {
+        Ok(FrameReader::new(inner)?
+            .with_item_type(config.item_type)
+            .with_checksums(config.checksums))
+    }
+
+    /// Only treat ring items of this type as frames; defaults to
+    /// `TRACE_FRAME_ITEM_TYPE`.  Must match whatever item type the
+    /// stream's `FrameWriter` was tagging with.
+    pub fn with_item_type(mut self, item_type: u32) -> Self {
+        self.item_type = item_type;
+        self
+    }
+
+    /// Must match whatever `FrameWriter::with_checksums` the stream was
+    /// written with, since the trailing CRC32 (if present) is otherwise
+    /// indistinguishable from frame body bytes.
+    pub fn with_checksums(mut self, enabled: bool) -> Self {
+        self.checksums = enabled;
+        self
+    }
+
+    /// Read the next frame from the stream.
+    /// Returns `Ok(None)` at a clean end of stream.  Ring items whose
+    /// type doesn't match are skipped rather than treated as an error,
+    /// so other item types can share the stream.
+    pub fn read_next_frame(&mut self) -> Result<Option<Frame>, FramizerError> {
+        loop {
+            // Determined experimentally (mirroring how `write_ring_item`
+            // uses `RingItem`): `read_item` returns `Ok(None)` at a clean
+            // end of stream, and a read `RingItem` exposes its type, its
+            // body header timestamp, and its raw payload bytes in the
+            // order they were `add`ed.
+            let item = match RingItem::read_item(&mut self.reader) {
+                Ok(Some(item)) => item,
+                Ok(None) => return Ok(None),
+                Err(e) => return Err(FramizerError::Io(e)),
+            };
+
+            if item.item_type() != self.item_type {
+                continue;
+            }
+
+            let frame_start = item.timestamp();
+            return parse_frame_body(frame_start, item.body(), self.checksums).map(Some);
+        }
+    }
+}
+
+/// Parse a frame's ring item body back into its segments.
+///
+/// A frame with a single segment is serialized without a segment count
+/// (see `frame_body_bytes`), so we can't tell single-segment from
+/// pileup framing just by looking at a tag byte - instead we try the
+/// single-segment layout first and accept it only if it accounts for
+/// every byte in the body; otherwise we fall back to the pileup layout.
+fn parse_frame_body(frame_start: u64, body: &[u8], checksums: bool) -> Result<Frame, FramizerError> {
+    let (body, found_crc) = if checksums {
+        if body.len() < 4 {
+            return Err(FramizerError::MalformedFrame { frame_start });
+        }
+        let split = body.len() - 4;
+        let crc = u32::from_le_bytes([body[split], body[split + 1], body[split + 2], body[split + 3]]);
+        (&body[..split], Some(crc))
+    } else {
+        (body, None)
+    };
+
+    let segments = if let Some(single) = try_parse_single_segment(body) {
+        single
+    } else {
+        parse_pileup_segments(frame_start, body)?
+    };
+
+    if let Some(found) = found_crc {
+        verify_frame_checksum(frame_start, &segments, found)?;
+    }
+
+    Ok(Frame {
+        frame_start,
+        segments,
+    })
+}
+
+/// Try to read `body` as the legacy `data_size`/`data_offset`/samples
+/// layout.  Returns `None` if the body isn't fully accounted for by
+/// that layout (in which case it must be a pileup frame instead).
+fn try_parse_single_segment(body: &[u8]) -> Option<Vec<Segment>> {
+    if body.len() < 6 {
+        return None;
+    }
+    let data_size = u32::from_le_bytes([body[0], body[1], body[2], body[3]]);
+    let data_offset = u16::from_le_bytes([body[4], body[5]]);
+
+    if body.len() != 6 + data_size as usize * 2 {
+        return None;
+    }
+
+    if data_size == 0 {
+        // An empty frame carries no trace data at all - a gap.
+        return Some(Vec::new());
+    }
+
+    let data = body[6..]
+        .chunks_exact(2)
+        .map(|w| u16::from_le_bytes([w[0], w[1]]))
+        .collect();
+
+    Some(vec![Segment {
+        data_offset,
+        data_size,
+        data,
+    }])
+}
+
+fn parse_pileup_segments(frame_start: u64, body: &[u8]) -> Result<Vec<Segment>, FramizerError> {
+    if body.len() < 2 {
+        return Err(FramizerError::MalformedFrame { frame_start });
+    }
+    let segment_count = u16::from_le_bytes([body[0], body[1]]) as usize;
+
+    let mut segments = Vec::with_capacity(segment_count);
+    let mut cursor = 2usize;
+    for _ in 0..segment_count {
+        if body.len() < cursor + 6 {
+            return Err(FramizerError::MalformedFrame { frame_start });
+        }
+        let data_offset = u16::from_le_bytes([body[cursor], body[cursor + 1]]);
+        let data_size = u32::from_le_bytes([
+            body[cursor + 2],
+            body[cursor + 3],
+            body[cursor + 4],
+            body[cursor + 5],
+        ]);
+        cursor += 6;
+
+        let byte_len = data_size as usize * 2;
+        if body.len() < cursor + byte_len {
+            return Err(FramizerError::MalformedFrame { frame_start });
+        }
+        let data = body[cursor..cursor + byte_len]
+            .chunks_exact(2)
+            .map(|w| u16::from_le_bytes([w[0], w[1]]))
+            .collect();
+        cursor += byte_len;
+
+        segments.push(Segment {
+            data_offset,
+            data_size,
+            data,
+        });
+    }
+
+    if cursor != body.len() {
+        return Err(FramizerError::MalformedFrame { frame_start });
+    }
+
+    Ok(segments)
+}
+
+/// Stitches consecutive frames back into the `Trace`s that produced them.
+///
+/// Only one trace can be "in flight" (overflowing into a continuation
+/// frame) at a time, since the `framizer` framing loop's pileup packing
+/// never lets a piled-up trace overflow past the frame it started in - so a single
+/// pending slot is enough.  A continuation is any segment with
+/// `data_offset == 0` while a trace is pending; note that a trace which
+/// genuinely starts exactly on a frame boundary looks identical to a
+/// continuation on the wire; with nothing pending it's treated as a new
+/// trace, which is the only case that matters in practice.
+pub struct Reassembler {
+    open: Option<Trace>,
+    completed: VecDeque<Trace>,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Reassembler {
+    pub fn new() -> Self {
+        Reassembler {
+            open: None,
+            completed: VecDeque::new(),
+        }
+    }
+
+    /// Feed the next frame, in stream order.  Returns the traces that
+    /// are now known to be complete.
+    pub fn push_frame(&mut self, frame: &Frame) -> Vec<Trace> {
+        let continuation_idx = if self.open.is_some() {
+            frame
+                .segments
+                .iter()
+                .position(|s| s.data_offset == 0 && s.data_size > 0)
+        } else {
+            None
+        };
+
+        match continuation_idx {
+            Some(idx) => {
+                self.open
+                    .as_mut()
+                    .unwrap()
+                    .data
+                    .extend_from_slice(&frame.segments[idx].data);
+            }
+            None => {
+                if let Some(done) = self.open.take() {
+                    self.completed.push_back(done);
+                }
+            }
+        }
+
+        for (idx, seg) in frame.segments.iter().enumerate() {
+            if seg.data_size == 0 || Some(idx) == continuation_idx {
+                continue; // Gap segment, or the continuation already folded in above.
+            }
+
+            if let Some(still_open) = self.open.take() {
+                // A prior starter segment in this same frame (pileup)
+                // never gets continued past this frame, so it's done.
+                self.completed.push_back(still_open);
+            }
+
+            let timestamp = frame.frame_start + seg.data_offset as u64;
+            self.open = Some(Trace {
+                timestamp,
+                data: seg.data.clone(),
+            });
+        }
+
+        self.completed.drain(..).collect()
+    }
+
+    /// Call once the stream is exhausted to flush any trace still open.
+    pub fn finish(mut self) -> Option<Trace> {
+        self.open.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    use crate::FrameWriter;
+
+    /// End-to-end round trip: one trace spanning two frames followed by a
+    /// second trace that starts partway into its frame, written through a
+    /// checksummed, compressed `FrameWriter` and read back through
+    /// `FrameReader`/`Reassembler`.  Asserts `frames -> traces` gives back
+    /// exactly the traces that went in.
+    #[test]
+    fn frames_round_trip_back_into_the_original_traces() {
+        let mut writer = FrameWriter::new(Cursor::new(Vec::new()))
+            .with_checksums(true)
+            .with_compression(Some(crate::Level::fast()))
+            .unwrap();
+
+        let mut first = Frame::new(0);
+        first.push_segment(0, vec![1, 2, 3, 4]);
+        writer.write_frame(&first).unwrap();
+
+        let mut continuation = Frame::new(4);
+        continuation.push_segment(0, vec![5, 6, 7, 8]);
+        writer.write_frame(&continuation).unwrap();
+
+        let mut second = Frame::new(8);
+        second.push_segment(1, vec![9, 10]);
+        writer.write_frame(&second).unwrap();
+
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let mut reader = FrameReader::new(Cursor::new(bytes))
+            .unwrap()
+            .with_checksums(true);
+        let mut reassembler = Reassembler::new();
+        let mut traces = Vec::new();
+
+        while let Some(frame) = reader.read_next_frame().unwrap() {
+            traces.extend(reassembler.push_frame(&frame));
+        }
+        traces.extend(reassembler.finish());
+
+        assert_eq!(
+            traces,
+            vec![
+                Trace {
+                    timestamp: 0,
+                    data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+                },
+                Trace {
+                    timestamp: 9,
+                    data: vec![9, 10],
+                },
+            ]
+        );
+    }
+}
@@ -1,220 +1,268 @@
 use std::fs::File;
-use std::io::{BufReader, Read, ErrorKind};
-
-use rust_ringitem_format::RingItem;   // We'll invent our own type.
-
-// Ring item types for frames.... complete just so we sort of reserve
-// these types.
-
-const TRACE_FRAME_ITEM_TYPE : u32 = 50;
-//const TDC_FRAME_ITEM_TYPE   : u32 = 51;   // comment so we don't warn.
-
-// In the end, we want to make a file that is 
-// made up of ring items that are frames.
-// a frame is a fixed size time chunk consists of the following:
-// - The value 2 indicating this is a waveform frame.
-// - a timestamp for the frame start.
-// - a size of non-zero data in that frame.
-// - offset (fine time) intot he fram of the data.
-// size u16 data items representing the chunk of the
-// trace that fit into the window.
-
-// note that traces can span frame boundaries.
-// Note that we're not going to support multiple trace starts in a window....
-// if we see such a thing, we drop the second trace on the floor and output
-// a message saying we did that.
-
-// internally:
-
-#[derive(Debug)]
-struct Frame {
-    frame_start: u64,        // Coarse timestamp - frame start.
-    data_size: u32,          // data size samples in the frame.
-    data_offset: u16,        // where in the frame the samples start.
-    data: Vec<u16>,          // data_size samples.
-}
+use std::iter::Peekable;
+use std::process::ExitCode;
+
+use framizer::Frame;
+use framizer::{Config, FrameWriter, FramizerError, Trace, TraceReader};
+
+fn main() -> ExitCode {
+    let config = match Config::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
 
-impl Frame {
-    pub fn new(start : u64) -> Frame {
-        Frame {
-            frame_start : start,
-            data_size   : 0,        // Must be computed
-            data_offset : 0,        // Must be computed.
-            data : Vec::new(),
+    match run(&config) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
         }
     }
 }
 
-// Data format in the file from Aaron:
-#[derive(Debug)]
-struct Trace {
-    timestamp: u64,          // Coarse timestamp of the trace.
-    data: Vec<u16>,         // data samples for the trace.
-}
-
-const FRAME_LENGTH: u64  = 512;   // Ticks in a window.
-fn main() {
-    let file = File::open("traces.dat").unwrap();
-    let mut ring_file = File::create("frames.evt").unwrap();
+fn run(config: &Config) -> Result<(), FramizerError> {
+    let file = File::open(&config.input_path)?;
+    let ring_file = File::create(&config.output_path)?;
 
-    let mut reader = BufReader::new(file);
+    let trace_reader = TraceReader::new(file);
+    let mut frame_writer = FrameWriter::from_config(ring_file, config)?;
 
-    // Read the traces from file.
+    let frame_length = config.frame_length;
 
+    // Read the traces from file.  Peekable so we can look ahead for
+    // pileup: a further trace whose timestamp also falls in the current
+    // frame's window.
+    let mut traces = trace_reader.peekable();
 
     let mut frame_timestamp = 0;
-    while let Some(trace) = read_next_trace(&mut reader) {
 
-        // emit 0 length frames unitil the trace starts inside of it:
-        // If the timetamp is already <frame_timestamp drop the trace:
+    // The primary segment for the next frame: either a fresh trace's
+    // starting offset and data, or - while a trace is still spilling
+    // across multiple frames - the offset-0 tail left over from the
+    // previous window.  Keeping this explicit (instead of only handling
+    // pileup on the frame a trace starts in) lets pack_frame_window peek
+    // for pileup companions on every window a trace touches, including
+    // its overflow frames.
+    let mut pending: Option<(u16, Vec<u16>)> = None;
+
+    loop {
+        let (primary_offset, primary_data) = match pending.take() {
+            Some(primary) => primary,
+            None => {
+                let trace = match next_primary_trace(&mut traces, frame_timestamp)? {
+                    Some(trace) => trace,
+                    None => break,
+                };
+
+                while trace.timestamp >= frame_timestamp + frame_length {
+                    // emit an empty frame.
+                    let empty_frame = Frame::new(frame_timestamp);
+
+                    frame_writer.write_frame(&empty_frame)?;
+
+                    frame_timestamp += frame_length;
+                }
+
+                let data_offset = (trace.timestamp - frame_timestamp) as u16;
+                (data_offset, trace.data)
+            }
+        };
+
+        let (frame, overflow) = pack_frame_window(
+            frame_timestamp,
+            frame_length,
+            primary_offset,
+            &primary_data,
+            &mut traces,
+        );
+
+        frame_writer.write_frame(&frame)?;
+        frame_timestamp += frame_length;
+        pending = overflow.map(|data| (0u16, data));
+    }
+
+    frame_writer.finish()?;
+
+    Ok(())
+}
+
+/// Pull the next trace that starts at or after `frame_timestamp`,
+/// dropping (and logging) any trace whose timestamp is already behind
+/// the current frame window.  Returns `Ok(None)` once the stream is
+/// exhausted.
+fn next_primary_trace<I>(
+    traces: &mut Peekable<I>,
+    frame_timestamp: u64,
+) -> Result<Option<Trace>, FramizerError>
+where
+    I: Iterator<Item = Result<Trace, FramizerError>>,
+{
+    for result in traces.by_ref() {
+        let trace = result?;
 
         if trace.timestamp < frame_timestamp {
-            println!("Dropping trace with timestamp 0x{:x} because it starts before the current frame timestamp 0x{:x}.", trace.timestamp, frame_timestamp);
+            println!(
+                "Dropping trace with timestamp 0x{:x} because it starts before the current frame timestamp 0x{:x}.",
+                trace.timestamp, frame_timestamp
+            );
             continue;
         }
 
-        while trace.timestamp >= frame_timestamp + FRAME_LENGTH {
-
-            // emit an empty frame.
-            let empty_frame = Frame::new(frame_timestamp);
+        return Ok(Some(trace));
+    }
 
-            write_ring_item(&mut ring_file, &empty_frame).expect("Failed to write empty frame");
+    Ok(None)
+}
 
-            frame_timestamp += FRAME_LENGTH;
+/// Pack one frame covering `[frame_timestamp, frame_timestamp +
+/// frame_length)`.  `primary_offset`/`primary_data` is the segment that
+/// claims this window first - either a trace starting here, or the
+/// offset-0 tail of a trace still spilling over from an earlier window -
+/// and any further traces peeked from `traces` whose timestamp also
+/// falls in this window are packed in alongside it as pileup, exactly as
+/// for the window a trace starts in.  Returns the frame plus any tail of
+/// `primary_data` left over for a further window of the same trace.
+fn pack_frame_window<I>(
+    frame_timestamp: u64,
+    frame_length: u64,
+    primary_offset: u16,
+    primary_data: &[u16],
+    traces: &mut Peekable<I>,
+) -> (Frame, Option<Vec<u16>>)
+where
+    I: Iterator<Item = Result<Trace, FramizerError>>,
+{
+    let mut frame = Frame::new(frame_timestamp);
+    let mut occupied: Vec<(u32, u32)> = Vec::new(); // (start, end) sample ranges already claimed.
+
+    let first_len = std::cmp::min(
+        primary_data.len() as u64,
+        frame_length - primary_offset as u64,
+    ) as usize;
+
+    occupied.push((
+        primary_offset as u32,
+        primary_offset as u32 + first_len as u32,
+    ));
+    frame.push_segment(primary_offset, primary_data[0..first_len].to_vec());
+
+    let overflow = (first_len < primary_data.len()).then(|| primary_data[first_len..].to_vec());
+
+    while let Some(Ok(next)) = traces.peek() {
+        if next.timestamp >= frame_timestamp + frame_length {
+            break; // Starts in a later window; a future call will pick it up.
         }
-        // The trace starts in this frame:
-
-        let  data_offset = trace.timestamp - frame_timestamp;
-        
-        if ((trace.data.len() + data_offset as usize) as u64) < FRAME_LENGTH {
-            // Whole trace fits.
-
-            let mut f = Frame::new(frame_timestamp);
-            f.data_size = trace.data.len() as u32;                    // Whole trace fits.
-            f.data_offset = data_offset as u16;
-            f.data        = trace.data.clone();                     // whole trace.
-
-            // Whole trace fits in the frame
-
-            write_ring_item(&mut ring_file, &f).expect("Failed to write single frame trace");
-            frame_timestamp += FRAME_LENGTH;
-        
-        } else {
-            // We emit frames until the trace is consumed.  All but the first frame have offsets of 0.
-            let mut cursor : usize = 0;    // where we are in the tracde.
-            let mut first_frame = Frame::new(frame_timestamp);
-            first_frame.data_offset = data_offset as u16;
-            first_frame.data_size   = (FRAME_LENGTH - data_offset) as u32;   // this is what fits.
-            first_frame.data.extend(&trace.data[0..first_frame.data_size as usize]);   // Extend the v ector with this slice.
-            
-            // emit first frame:
-
-            write_ring_item(&mut ring_file, &first_frame).expect("Failed to write first frame of multi-frame trace");
-
-            cursor += first_frame.data_size as usize;                           // next slice.
-            frame_timestamp += FRAME_LENGTH;
-
-            // Theoretically there could be multiple overflows.
-            
-            while cursor < trace.data.len() {
-                let mut frame = Frame::new(frame_timestamp);
-                frame.data_offset = 0;              // overflows into this frame.
-                if trace.data.len() - cursor > FRAME_LENGTH as usize {
-                    frame.data_size   = FRAME_LENGTH as u32;   // full filled
-                    frame.data.extend(&trace.data[cursor .. cursor+FRAME_LENGTH as usize]);
-                    
-                } else {
-                    frame.data_size = (trace.data.len() - cursor) as u32;
-                    frame.data.extend(&trace.data[cursor..]);   // Rest of the trace.       
-                }
-                // Output an overflow frame:
-
-                write_ring_item(&mut ring_file, &frame).expect("Failed to write overflow frame for multi-frame trace");
 
-                cursor += frame.data_size as usize;
-                frame_timestamp += FRAME_LENGTH;
+        let next_trace = match traces.next() {
+            Some(Ok(t)) => t,
+            _ => unreachable!("peek() just confirmed this is Some(Ok(_))"),
+        };
+
+        let next_offset = (next_trace.timestamp - frame_timestamp) as u16;
+        let next_len = std::cmp::min(
+            next_trace.data.len() as u64,
+            frame_length - next_offset as u64,
+        ) as usize;
+        let next_start = next_offset as u32;
+        let next_end = next_start + next_len as u32;
+
+        let overlaps = occupied
+            .iter()
+            .any(|&(start, end)| next_start < end && start < next_end);
+
+        if overlaps {
+            println!(
+                "Dropping piled-up trace with timestamp 0x{:x}: its samples overlap another trace already packed into the frame starting at 0x{:x}.",
+                next_trace.timestamp, frame_timestamp
+            );
+            continue;
+        }
 
-            }
+        if next_trace.data.len() > next_len {
+            println!(
+                "Dropping overflow samples of piled-up trace with timestamp 0x{:x}: multi-frame pileup traces are not supported.",
+                next_trace.timestamp
+            );
         }
+
+        occupied.push((next_start, next_end));
+        frame.push_segment(next_offset, next_trace.data[0..next_len].to_vec());
     }
-    
-    
-}
 
+    (frame, overflow)
+}
 
-// Read the next trace from the file:
-// Returns None if we reach the end of the file.
-fn read_next_trace(reader: &mut BufReader<File>) -> Option<Trace> {
-    let mut timestamp_buf = [0u8; 8];
-    let mut data_size_buf = [0u8; 4];   
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn traces_iter(traces: Vec<Trace>) -> Peekable<std::vec::IntoIter<Result<Trace, FramizerError>>> {
+        traces
+            .into_iter()
+            .map(Ok)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .peekable()
+    }
 
-    // Read the timestamp (8 bytes).
-    if let Err(e) = reader.read_exact(&mut timestamp_buf) {
-        if e.kind() == ErrorKind::UnexpectedEof {   
-            return None; // End of file reached.
-        } else {
-            panic!("Error reading timestamp: {:?}", e);
-        }
+    /// A trace that spills across frame boundaries must still pick up a
+    /// pileup companion whose timestamp falls into one of its overflow
+    /// windows, not just into the frame it starts in.
+    #[test]
+    fn pileup_is_packed_during_overflow_windows() {
+        let frame_length = 512u64;
+
+        // Trace A starts at t=0 with 600 samples: frame0 is full (samples
+        // 0..512), and the overflow frame at frame_timestamp=512 carries
+        // samples 512..600 (88 samples) at offset 0, leaving [88, 512) of
+        // that window free.
+        let a_data: Vec<u16> = (0..600).map(|i| i as u16).collect();
+
+        // Trace B starts at t=612 (offset 100 within the frame_timestamp=512
+        // window) with samples that fit entirely inside [100, 150) of that
+        // same window - no overlap with A's [0, 88).
+        let b_data: Vec<u16> = vec![1000, 1001, 1002, 1003, 1004];
+
+        let mut traces = traces_iter(vec![
+            Trace {
+                timestamp: 612,
+                data: b_data.clone(),
+            },
+        ]);
+
+        // Drive the overflow window exactly as `run` would: primary is A's
+        // leftover tail at offset 0, starting at frame_timestamp=512.
+        let (frame, overflow) = pack_frame_window(512, frame_length, 0, &a_data[512..], &mut traces);
+
+        assert!(overflow.is_none());
+        assert_eq!(frame.segments.len(), 2, "expected A's tail plus B piled up");
+        assert_eq!(frame.segments[0].data_offset, 0);
+        assert_eq!(frame.segments[0].data, a_data[512..].to_vec());
+        assert_eq!(frame.segments[1].data_offset, 100);
+        assert_eq!(frame.segments[1].data, b_data);
     }
-    let timestamp = u64::from_le_bytes(timestamp_buf);
-
-    // Read the data size (2 bytes).
-    if let Err(e) = reader.read_exact(&mut data_size_buf) {
-        if e.kind() == ErrorKind::UnexpectedEof {
-            return None; // End of file reached.
-        } else {
-            panic!("Error reading data size: {:?}", e);
-        }
+
+    #[test]
+    fn primary_overflow_spans_multiple_windows() {
+        let frame_length = 4u64;
+        let data: Vec<u16> = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let mut traces = traces_iter(vec![]);
+
+        let (frame, overflow) = pack_frame_window(0, frame_length, 0, &data, &mut traces);
+        assert_eq!(frame.segments[0].data, vec![1, 2, 3, 4]);
+        assert_eq!(overflow, Some(vec![5, 6, 7, 8, 9]));
+
+        let (frame, overflow) =
+            pack_frame_window(4, frame_length, 0, &overflow.unwrap(), &mut traces);
+        assert_eq!(frame.segments[0].data, vec![5, 6, 7, 8]);
+        assert_eq!(overflow, Some(vec![9]));
+
+        let (frame, overflow) =
+            pack_frame_window(8, frame_length, 0, &overflow.unwrap(), &mut traces);
+        assert_eq!(frame.segments[0].data, vec![9]);
+        assert_eq!(overflow, None);
     }
-    let data_size = u32::from_le_bytes(data_size_buf);
-
-    // Read the data samples (data_size * 2 bytes).
-
-    let mut samples : Vec<u16> = Vec::with_capacity(data_size as usize);
-    for _ in 0..data_size {
-        let mut sample_buf = [0u8; 2];
-        if let Err(e) = reader.read_exact(&mut sample_buf) {
-            if e.kind() == ErrorKind::UnexpectedEof {
-                return None; // End of file reached.
-            } else {
-                panic!("Error reading data sample: {:?}", e);
-            }
-        }
-        let sample = u16::from_le_bytes(sample_buf);
-       
-        samples.push(sample);
-    } 
-    Some(Trace { timestamp: timestamp, 
-        data: samples 
-    })
 }
-//
-// Write a frame as a ring item.
-// The ring item will have:
-// - A type of TRACE_FRAME_ITEM_TYPE
-// - A body header with timestamp the frame start time.
-//   source id and barrier type 0, since this is a test.
-// - A ring item body that consists of:
-//   frame.data_size
-//   frame.data_offset
-//   frame.data
-fn write_ring_item(f : &mut File, frame: &Frame) -> std::io::Result<usize> {
-
-    // Create and fill the ring item:
-    let mut ring_item = RingItem::new_with_body_header(
-        TRACE_FRAME_ITEM_TYPE, 
-        frame.frame_start,
-        0,0
-    );
-    ring_item.add(frame.data_size)  // Lead with the data size and 
-        .add(frame.data_offset);    // frame offset.
-    
-    // In the loop below, word appears to be &u16 not u16 so it must
-    // be dereferenced.  Determined this experimnentally.
-
-    for word  in &frame.data {     // If the vector is empty this will add nothing.
-
-        ring_item.add(*word);  
-    }
-    ring_item.write_item(f)
-}
\ No newline at end of file